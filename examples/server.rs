@@ -15,5 +15,6 @@ fn setup_discoverable_server(mut commands: Commands) {
     commands.insert_resource(DiscoverableServer {
         port: 1234,
         metadata: ServerMetadata::new().with("name", "TestServer"),
+        ..default()
     });
 }
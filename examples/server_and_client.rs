@@ -26,6 +26,7 @@ fn setup_discoverable_server(mut commands: Commands) {
             .with("name", "TestServer")
             .with("players", 0)
             .with("max_players", 4),
+        ..default()
     });
 }
 
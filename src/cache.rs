@@ -0,0 +1,171 @@
+//! On-disk cache of previously [`DiscoveredServer`]s, so a server seen in a
+//! prior session (especially one found through the master server) can be
+//! shown and connected to immediately on startup, before any fresh
+//! discovery completes. See [`crate::ServerBrowserPlugin::with_server_cache`].
+
+use std::{
+    fs,
+    io,
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_utils::tracing::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{DiscoveredServer, DiscoveredServerList, DiscoveredServerStatus, ServerMetadata};
+
+/// How often [`save_cache`] writes the current [`DiscoveredServerList`] to
+/// disk while it keeps changing.
+const SAVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Registers the cache resources and systems on `app`. Called from
+/// [`crate::ServerBrowserPlugin::build`] when a cache path was provided.
+pub(crate) fn build(app: &mut App, path: PathBuf) {
+    app.insert_resource(CacheConfig { path });
+    app.insert_resource(CacheSaveState::default());
+    app.add_systems(Startup, load_cache);
+    app.add_systems(PostUpdate, save_cache);
+}
+
+#[derive(Resource)]
+struct CacheConfig {
+    path: PathBuf,
+}
+
+/// Tracks when we last wrote the cache file and whether anything has
+/// changed since, so [`save_cache`] writes at most once per
+/// [`SAVE_INTERVAL`] instead of every single frame while the list keeps
+/// changing (e.g. under continuous discovery, see
+/// [`crate::ServerBrowserPlugin::with_continuous_discovery`]).
+#[derive(Resource, Default)]
+struct CacheSaveState {
+    last_saved: Option<Instant>,
+    dirty: bool,
+}
+
+/// On-disk representation of a single [`DiscoveredServer`], keyed the same
+/// way it is stored in [`DiscoveredServerList`] so that, once the same
+/// server is discovered again, the normal `upsert` overwrites this cached
+/// entry in place instead of the two coexisting.
+#[derive(Serialize, Deserialize)]
+struct CachedServer {
+    key: String,
+    hostname: String,
+    port: u16,
+    addresses: Vec<IpAddr>,
+    metadata: Vec<(String, String)>,
+    version: u32,
+}
+
+/// Top-level on-disk format written to the cache file.
+#[derive(Serialize, Deserialize, Default)]
+struct CacheFile {
+    servers: Vec<CachedServer>,
+}
+
+fn load_cache(config: Res<CacheConfig>, mut discovered_servers: ResMut<DiscoveredServerList>) {
+    let bytes = match fs::read(&config.path) {
+        Ok(bytes) => bytes,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return,
+        Err(error) => {
+            warn!("Failed to read server cache {:?}: {error}", config.path);
+            return;
+        }
+    };
+
+    let cache: CacheFile = match serde_json::from_slice(&bytes) {
+        Ok(cache) => cache,
+        Err(error) => {
+            warn!("Failed to parse server cache {:?}: {error}", config.path);
+            return;
+        }
+    };
+
+    // this runs at Startup before anything could have discovered a server
+    // yet, so every entry we insert here is genuinely new
+    let servers = discovered_servers.bypass_change_detection();
+    let mut changed = false;
+
+    for cached in cache.servers {
+        let mut metadata = ServerMetadata::new();
+        for (key, value) in cached.metadata {
+            metadata.set(key, value);
+        }
+
+        let server = DiscoveredServer {
+            hostname: cached.hostname,
+            port: cached.port,
+            addresses: cached.addresses.into_iter().collect(),
+            metadata,
+            version: cached.version,
+            outdated: false,
+            status: DiscoveredServerStatus::Cached,
+        };
+
+        changed |= servers.upsert(&cached.key, server);
+    }
+
+    if changed {
+        discovered_servers.set_changed();
+    }
+}
+
+fn save_cache(
+    config: Res<CacheConfig>,
+    mut state: ResMut<CacheSaveState>,
+    discovered_servers: Res<DiscoveredServerList>,
+) {
+    state.dirty |= discovered_servers.is_changed();
+
+    let due = state
+        .last_saved
+        .map_or(true, |last_saved| last_saved.elapsed() >= SAVE_INTERVAL);
+
+    // only actually hit disk once per `SAVE_INTERVAL`; `dirty` just tracks
+    // whether there is anything new to write once that interval is up
+    if !state.dirty || !due {
+        return;
+    }
+
+    let cache = CacheFile {
+        servers: discovered_servers
+            .iter_with_keys()
+            // entries with no address (e.g. the master's "update required"
+            // sentinel) can't be connected to, so don't resurface them as
+            // cached servers on the next startup
+            .filter(|(_, server)| !server.addresses.is_empty())
+            .map(|(key, server)| CachedServer {
+                key: key.to_string(),
+                hostname: server.hostname.clone(),
+                port: server.port,
+                addresses: server.addresses.iter().copied().collect(),
+                metadata: server
+                    .metadata
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                version: server.version,
+            })
+            .collect(),
+    };
+
+    let bytes = match serde_json::to_vec(&cache) {
+        Ok(bytes) => bytes,
+        Err(error) => {
+            warn!("Failed to serialize server cache: {error}");
+            return;
+        }
+    };
+
+    match fs::write(&config.path, bytes) {
+        Ok(()) => {
+            state.last_saved = Some(Instant::now());
+            state.dirty = false;
+        }
+        Err(error) => warn!("Failed to write server cache {:?}: {error}", config.path),
+    }
+}
@@ -0,0 +1,132 @@
+use crate::ServerMetadata;
+
+/// Metadata keys used by the [`ServerFilter::not_empty`] and
+/// [`ServerFilter::not_full`] convenience predicates.
+const PLAYERS_KEY: &str = "players";
+const MAX_PLAYERS_KEY: &str = "max_players";
+
+/// A numeric comparison operator for [`ServerFilter::metadata_compare`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericOp {
+    /// The metadata value must equal the filter value.
+    Equal,
+    /// The metadata value must be less than the filter value.
+    Less,
+    /// The metadata value must be less than or equal to the filter value.
+    LessOrEqual,
+    /// The metadata value must be greater than the filter value.
+    Greater,
+    /// The metadata value must be greater than or equal to the filter value.
+    GreaterOrEqual,
+}
+
+impl NumericOp {
+    fn matches(self, lhs: i64, rhs: i64) -> bool {
+        match self {
+            NumericOp::Equal => lhs == rhs,
+            NumericOp::Less => lhs < rhs,
+            NumericOp::LessOrEqual => lhs <= rhs,
+            NumericOp::Greater => lhs > rhs,
+            NumericOp::GreaterOrEqual => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Predicate {
+    Equals {
+        key: String,
+        value: String,
+    },
+    NotEmpty,
+    NotFull,
+    Compare {
+        key: String,
+        op: NumericOp,
+        value: i64,
+    },
+}
+
+impl Predicate {
+    fn matches(&self, metadata: &ServerMetadata) -> bool {
+        match self {
+            Predicate::Equals { key, value } => metadata.get(key) == Some(value.as_str()),
+            Predicate::NotEmpty => metadata_int(metadata, PLAYERS_KEY).unwrap_or(0) > 0,
+            Predicate::NotFull => {
+                let players = metadata_int(metadata, PLAYERS_KEY).unwrap_or(0);
+                let max_players = metadata_int(metadata, MAX_PLAYERS_KEY).unwrap_or(0);
+                players < max_players
+            }
+            Predicate::Compare { key, op, value } => match metadata_int(metadata, key) {
+                Some(metadata_value) => op.matches(metadata_value, *value),
+                None => false,
+            },
+        }
+    }
+}
+
+fn metadata_int(metadata: &ServerMetadata, key: &str) -> Option<i64> {
+    metadata.get(key)?.parse().ok()
+}
+
+/// A filter over a discovered server's metadata, mirroring the xash3d
+/// `QueryServers` filter. Build one with [`ServerFilter::new`] and chain
+/// predicates onto it; a server found on the local network must satisfy
+/// every predicate to be reported by [`SearchServers`](crate::SearchServers).
+/// Servers found through the master server and entries restored from the
+/// on-disk cache are not filtered. An empty filter (the default) matches
+/// every server.
+///
+/// ```
+/// let filter = ServerFilter::new()
+///     .metadata_equals("gamemode", "ctf")
+///     .not_full();
+/// ```
+#[derive(Clone, Default)]
+pub struct ServerFilter(Vec<Predicate>);
+
+impl ServerFilter {
+    /// Returns a new filter that matches every server.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Only match servers whose metadata `key` is exactly `value`.
+    pub fn metadata_equals<K: AsRef<str>, V: ToString>(mut self, key: K, value: V) -> Self {
+        self.0.push(Predicate::Equals {
+            key: key.as_ref().to_string(),
+            value: value.to_string(),
+        });
+        self
+    }
+
+    /// Only match servers reporting at least one player, i.e. whose
+    /// `"players"` metadata value is greater than zero.
+    pub fn not_empty(mut self) -> Self {
+        self.0.push(Predicate::NotEmpty);
+        self
+    }
+
+    /// Only match servers with an open slot, i.e. whose `"players"` metadata
+    /// value is lower than their `"max_players"` value.
+    pub fn not_full(mut self) -> Self {
+        self.0.push(Predicate::NotFull);
+        self
+    }
+
+    /// Only match servers whose metadata `key`, parsed as an integer,
+    /// satisfies `op` against `value`.
+    pub fn metadata_compare<K: AsRef<str>>(mut self, key: K, op: NumericOp, value: i64) -> Self {
+        self.0.push(Predicate::Compare {
+            key: key.as_ref().to_string(),
+            op,
+            value,
+        });
+        self
+    }
+
+    /// Returns true if `metadata` satisfies every predicate in this filter.
+    pub(crate) fn matches(&self, metadata: &ServerMetadata) -> bool {
+        self.0.iter().all(|predicate| predicate.matches(metadata))
+    }
+}
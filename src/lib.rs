@@ -1,9 +1,12 @@
 #![warn(missing_docs)]
 //! Bevy game engine plugin for creating and searching discoverable servers on local networks
+//! and, optionally, across the internet via a master server.
 
 use std::{
-    collections::{HashMap, HashSet},
-    net::IpAddr,
+    collections::HashSet,
+    net::{IpAddr, SocketAddr},
+    path::PathBuf,
+    time::{Duration, Instant},
 };
 
 use bevy_app::prelude::*;
@@ -16,17 +19,31 @@ use bevy_utils::{
 };
 use mdns_sd::{DaemonEvent, Receiver, ServiceDaemon, ServiceEvent, ServiceInfo};
 
+mod cache;
+mod filter;
+mod master;
+mod server_metadata;
+
+pub use filter::{NumericOp, ServerFilter};
+pub use server_metadata::ServerMetadata;
+
 pub mod prelude {
     //! Prelude containing all types you need for making discoverable server and for discovering servers.
     pub use crate::{
-        DiscoverableServer, DiscoveredServer, DiscoveredServerList, SearchServers,
-        ServerBrowserPlugin,
+        DiscoverableServer, DiscoveredServer, DiscoveredServerList, DiscoveredServerStatus,
+        NumericOp, SearchServers, ServerBrowserPlugin, ServerFilter, ServerMetadata,
     };
 }
 
+/// Reserved metadata key used to smuggle [`DiscoverableServer::version`]
+/// over the mDNS TXT record alongside user-provided metadata. Stripped back
+/// out of [`DiscoveredServer::metadata`] when a service is resolved.
+const VERSION_METADATA_KEY: &str = "__bevy_server_browser_version";
+
 /// Resource that when added makes server available for discovery
-/// on local network.
-#[derive(Resource)]
+/// on local network and, if [`ServerBrowserPlugin::with_master`] was used,
+/// on the configured master server.
+#[derive(Resource, Default)]
 pub struct DiscoverableServer {
     /// Arbitrary port that you want to report to clients to use.
     /// This is just information for clients, no binding or connecting
@@ -36,10 +53,15 @@ pub struct DiscoverableServer {
     /// Additional metadata to be sent to clients. You can add information such
     /// as the user-facing name of a server, current level loaded on server,
     /// current number of players, etc.
-    pub metadata: HashMap<String, String>,
+    pub metadata: ServerMetadata,
+
+    /// Protocol/app version of this server, sent alongside `metadata` and
+    /// compared against [`SearchServers::client_version`] so clients can
+    /// tell incompatible servers apart, see [`DiscoveredServer::outdated`].
+    pub version: u32,
 }
 
-/// Contains info about discovered server on local network.
+/// Contains info about discovered server on local network or on the internet.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DiscoveredServer {
     /// Hostname or name of a computer the server runs on.
@@ -54,8 +76,42 @@ pub struct DiscoveredServer {
     /// order or just use the first one
     pub addresses: HashSet<IpAddr>,
 
-    /// Additional metadata received from server, see [`DiscoverableServer::metadata`]
-    pub metadata: HashMap<String, String>,
+    /// Additional metadata received from server, see [`DiscoverableServer::metadata`].
+    /// Servers discovered through the master server do not carry any metadata,
+    /// as the master only relays addresses.
+    pub metadata: ServerMetadata,
+
+    /// Protocol/app version the server is advertising, see
+    /// [`DiscoverableServer::version`].
+    pub version: u32,
+
+    /// `true` when this server's [`version`](Self::version) does not match
+    /// the searching client's [`SearchServers::client_version`], meaning the
+    /// two are likely incompatible. The UI should gray out such servers and
+    /// prompt the user to update instead of letting them connect.
+    pub outdated: bool,
+
+    /// Whether this entry has been confirmed by a discovery backend this
+    /// session, or was loaded from the on-disk cache and not seen since, see
+    /// [`ServerBrowserPlugin::with_server_cache`].
+    pub status: DiscoveredServerStatus,
+}
+
+/// Whether a [`DiscoveredServer`] reflects a server actually confirmed by a
+/// discovery backend this session, or one restored from the on-disk cache
+/// that has not been seen yet. See
+/// [`ServerBrowserPlugin::with_server_cache`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiscoveredServerStatus {
+    /// Confirmed by a discovery backend (mDNS or the master server) during
+    /// this session.
+    Live,
+
+    /// Loaded from the on-disk cache at startup. The address may no longer
+    /// be reachable; the UI should let the user try connecting directly but
+    /// should not treat it as verified. Replaced with a [`Live`](Self::Live)
+    /// entry as soon as the same server is discovered again.
+    Cached,
 }
 
 /// Resource containing all servers discovered on local network.
@@ -86,6 +142,39 @@ impl DiscoveredServerList {
     pub fn iter(&self) -> impl Iterator<Item = &DiscoveredServer> {
         self.0.values().into_iter()
     }
+
+    /// Iterates over all discovered servers together with the internal key
+    /// they are stored under, so [`crate::cache`] can persist and later
+    /// overwrite the exact same entry once it is confirmed again.
+    pub(crate) fn iter_with_keys(&self) -> impl Iterator<Item = (&str, &DiscoveredServer)> {
+        self.0.iter().map(|(key, server)| (key.as_str(), server))
+    }
+
+    /// Inserts or updates a discovered server behind `key`, returning `true`
+    /// if the list actually changed, shared by every discovery backend
+    /// (mDNS, master server, ...) so they agree on change detection.
+    pub(crate) fn upsert(&mut self, key: &str, server: DiscoveredServer) -> bool {
+        match self.0.entry_ref(key) {
+            EntryRef::Occupied(mut entry) => {
+                if entry.get() == &server {
+                    false
+                } else {
+                    entry.insert(server);
+                    true
+                }
+            }
+            EntryRef::Vacant(entry) => {
+                entry.insert(server);
+                true
+            }
+        }
+    }
+
+    /// Removes the discovered server behind `key`, returning `true` if it
+    /// was present.
+    pub(crate) fn forget(&mut self, key: &str) -> bool {
+        self.0.remove(key).is_some()
+    }
 }
 
 impl<'a> IntoIterator for &'a DiscoveredServerList {
@@ -106,11 +195,38 @@ impl<'a> IntoIterator for &'a DiscoveredServerList {
 /// }
 /// ```
 #[derive(Event, Default)]
-pub struct SearchServers;
+pub struct SearchServers {
+    /// Only report servers whose metadata satisfies every predicate of this
+    /// filter, or `None` to report every discovered server. See
+    /// [`ServerFilter`]. Only servers found on the local network are
+    /// filtered: servers found through the master server carry no metadata
+    /// and are reported unfiltered, as are entries restored from the
+    /// on-disk cache.
+    pub filter: Option<ServerFilter>,
+
+    /// Your own protocol/app version, compared against every discovered
+    /// server's [`DiscoverableServer::version`] to flag incompatible
+    /// servers, see [`DiscoveredServer::outdated`].
+    pub client_version: u32,
+}
+
+/// Resource tracking the filter and client version of the most recent
+/// [`SearchServers`] event. The mDNS merge logic applies both; the master
+/// backend only uses the client version.
+#[derive(Resource, Default)]
+pub(crate) struct ActiveSearch {
+    pub(crate) filter: ServerFilter,
+    pub(crate) client_version: u32,
+}
 
 /// Plugin for servers and clients to discover each other.
 /// Add this to bevy app to use server or client functionality.
-pub struct ServerBrowserPlugin(String);
+pub struct ServerBrowserPlugin {
+    name: String,
+    master: Option<master::MasterConfig>,
+    continuous_discovery: Option<Duration>,
+    cache_path: Option<PathBuf>,
+}
 
 impl ServerBrowserPlugin {
     /// Create ServerBrowserPlugin
@@ -134,20 +250,112 @@ impl ServerBrowserPlugin {
     ///     .run();
     /// ```
     pub fn new(name: &str) -> Self {
-        Self(validate_name(name))
+        Self {
+            name: validate_name(name),
+            master: None,
+            continuous_discovery: None,
+            cache_path: None,
+        }
+    }
+
+    /// Additionally discover and announce servers across the internet through
+    /// a master server listening on `addr`. This coexists with the local
+    /// network (mDNS) discovery: [`DiscoveredServerList`] aggregates servers
+    /// found through both backends.
+    ///
+    /// ```
+    /// App::new()
+    ///     .add_plugins(DefaultPlugins)
+    ///     .add_plugins(
+    ///         ServerBrowserPlugin::new(env!("CARGO_PKG_NAME"))
+    ///             .with_master("123.45.67.89:27010".parse().unwrap()),
+    ///     )
+    ///     .run();
+    /// ```
+    pub fn with_master(mut self, addr: SocketAddr) -> Self {
+        self.master = Some(master::MasterConfig::new(addr));
+        self
+    }
+
+    /// Like [`Self::with_master`], but overrides the master-server backend's
+    /// timeouts instead of using the xash3d-derived defaults: how often an
+    /// announced [`DiscoverableServer`] re-announces itself
+    /// (`announce_interval`), how long we wait for the master's challenge
+    /// reply before retrying an announce (`challenge_timeout`), and how long
+    /// a master-discovered server is kept without being returned by a query
+    /// again before it is pruned (`entry_timeout`, xash3d defaults to ~300s).
+    pub fn with_master_timeouts(
+        mut self,
+        addr: SocketAddr,
+        announce_interval: Duration,
+        challenge_timeout: Duration,
+        entry_timeout: Duration,
+    ) -> Self {
+        self.master = Some(master::MasterConfig {
+            addr,
+            announce_interval,
+            challenge_timeout,
+            entry_timeout,
+        });
+        self
+    }
+
+    /// Keep the mDNS browse started by the first [`SearchServers`] event
+    /// running across later [`SearchServers`] events instead of clearing the
+    /// list and restarting the browse on each of them. A server whose entry is
+    /// not re-resolved within `stale_timeout` is pruned from
+    /// [`DiscoveredServerList`], so a browser UI left open reflects servers
+    /// appearing and disappearing in near real time instead of showing a
+    /// frozen snapshot until the user sends another [`SearchServers`].
+    ///
+    /// Without this, [`SearchServers`] is one-shot: it clears the list
+    /// (except entries restored from the cache, see
+    /// [`Self::with_server_cache`]), browses once, and the browse is torn
+    /// down and restarted by the next event.
+    pub fn with_continuous_discovery(mut self, stale_timeout: Duration) -> Self {
+        self.continuous_discovery = Some(stale_timeout);
+        self
+    }
+
+    /// Persist [`DiscoveredServerList`] to `path` and restore it on the next
+    /// startup, so a previously seen server (especially one found through
+    /// the master server) can be shown and connected to immediately,
+    /// before any fresh discovery completes. Restored entries are inserted
+    /// with [`DiscoveredServerStatus::Cached`] and replaced with a
+    /// [`DiscoveredServerStatus::Live`] entry as soon as a discovery backend
+    /// confirms the same server again.
+    pub fn with_server_cache(mut self, path: impl Into<PathBuf>) -> Self {
+        self.cache_path = Some(path.into());
+        self
     }
 }
 
 #[derive(Resource)]
 struct Logger(Receiver<DaemonEvent>);
 
+/// Configuration for continuous discovery mode, see
+/// [`ServerBrowserPlugin::with_continuous_discovery`]. Only inserted when
+/// that builder method was used.
+#[derive(Resource, Clone, Copy)]
+struct ContinuousDiscovery {
+    stale_timeout: Duration,
+}
+
+/// Tracks when each mDNS-discovered server was last re-resolved, so
+/// [`prune_stale_servers`] can expire entries that stop being seen. Kept as
+/// a parallel map instead of a field on [`DiscoveredServer`] to avoid
+/// polluting its `PartialEq`. Only inserted alongside [`ContinuousDiscovery`].
+#[derive(Resource, Default)]
+struct LastResolved(StableHashMap<String, Instant>);
+
 impl Plugin for ServerBrowserPlugin {
     fn build(&self, app: &mut App) {
         app.insert_resource(Service {
-            name: self.0.clone(),
+            name: self.name.clone(),
             daemon: ServiceDaemon::new().expect("Could not create service daemon"),
         });
         app.insert_resource(DiscoveredServerList(default()));
+        app.insert_resource(ActiveSearch::default());
         app.add_event::<SearchServers>();
         app.add_systems(Startup, setup_logger);
         app.add_systems(
@@ -165,8 +373,22 @@ impl Plugin for ServerBrowserPlugin {
             (
                 log_daemon_events.run_if(resource_exists::<Logger>()),
                 update_discovered_servers.run_if(resource_exists::<Searching>()),
+                prune_stale_servers.run_if(resource_exists::<ContinuousDiscovery>()),
             ),
         );
+
+        if let Some(stale_timeout) = self.continuous_discovery {
+            app.insert_resource(ContinuousDiscovery { stale_timeout });
+            app.insert_resource(LastResolved::default());
+        }
+
+        if let Some(config) = self.master {
+            master::build(app, config);
+        }
+
+        if let Some(path) = self.cache_path.clone() {
+            cache::build(app, path);
+        }
     }
 }
 
@@ -186,7 +408,9 @@ struct Searching {
 
 fn update_discovered_servers(
     browsing: Res<Searching>,
+    active_search: Res<ActiveSearch>,
     mut discovered_servers: ResMut<DiscoveredServerList>,
+    mut last_resolved: Option<ResMut<LastResolved>>,
 ) {
     // this functions does comlicated mutation by inserting and merging found
     // servers that would trigger change detection even on accesses, we bypass
@@ -201,10 +425,18 @@ fn update_discovered_servers(
         match event {
             ServiceEvent::ServiceResolved(info) => {
                 let hostname = info.get_hostname();
-
-                let mut metadata = HashMap::new();
-                for property in info.get_properties().iter() {
-                    metadata.insert(property.key().to_string(), property.val_str().to_string());
+                let mut metadata = ServerMetadata::from_txt_properties(info.get_properties());
+                let version = metadata
+                    .remove(VERSION_METADATA_KEY)
+                    .and_then(|version| version.parse().ok())
+                    .unwrap_or(0);
+
+                if !active_search.filter.matches(&metadata) {
+                    changed |= servers.forget(info.get_fullname());
+                    if let Some(last_resolved) = &mut last_resolved {
+                        last_resolved.0.remove(info.get_fullname());
+                    }
+                    continue;
                 }
 
                 let server = DiscoveredServer {
@@ -215,24 +447,24 @@ fn update_discovered_servers(
                     port: info.get_port(),
                     addresses: info.get_addresses().to_owned(),
                     metadata,
+                    outdated: version != active_search.client_version,
+                    version,
+                    status: DiscoveredServerStatus::Live,
                 };
 
-                match servers.0.entry_ref(info.get_fullname()) {
-                    EntryRef::Occupied(mut entry) => {
-                        if entry.get() != &server {
-                            changed = true;
-                            entry.insert(server);
-                        }
-                    }
-                    EntryRef::Vacant(entry) => {
-                        changed = true;
-                        entry.insert(server);
-                    }
+                if let Some(last_resolved) = &mut last_resolved {
+                    last_resolved
+                        .0
+                        .insert(info.get_fullname().to_string(), Instant::now());
                 }
+
+                changed |= servers.upsert(info.get_fullname(), server);
             }
             ServiceEvent::ServiceRemoved(_, fullname) => {
-                changed = true;
-                servers.0.remove(&fullname);
+                changed |= servers.forget(&fullname);
+                if let Some(last_resolved) = &mut last_resolved {
+                    last_resolved.0.remove(&fullname);
+                }
             }
             _ => {}
         }
@@ -243,20 +475,70 @@ fn update_discovered_servers(
     }
 }
 
+/// Prunes [`DiscoveredServerList`] of mDNS entries that have not been
+/// re-resolved within [`ContinuousDiscovery::stale_timeout`], using the
+/// timestamps [`update_discovered_servers`] records in [`LastResolved`].
+/// Only runs when [`ServerBrowserPlugin::with_continuous_discovery`] was
+/// used.
+fn prune_stale_servers(
+    config: Res<ContinuousDiscovery>,
+    mut last_resolved: ResMut<LastResolved>,
+    mut discovered_servers: ResMut<DiscoveredServerList>,
+) {
+    // see the comment on the mDNS merge in `update_discovered_servers` for
+    // why we bypass change detection here
+    let servers = discovered_servers.bypass_change_detection();
+    let mut changed = false;
+
+    last_resolved.0.retain(|key, last_seen| {
+        if last_seen.elapsed() < config.stale_timeout {
+            true
+        } else {
+            changed |= servers.forget(key);
+            false
+        }
+    });
+
+    if changed {
+        discovered_servers.set_changed();
+    }
+}
+
 fn search_servers(
     mut commands: Commands,
     service: Res<Service>,
+    continuous: Option<Res<ContinuousDiscovery>>,
+    searching: Option<Res<Searching>>,
     mut discovered_servers: ResMut<DiscoveredServerList>,
+    mut active_search: ResMut<ActiveSearch>,
     mut search_servers_event: EventReader<SearchServers>,
 ) {
-    if search_servers_event.is_empty() {
-        return;
+    let event = search_servers_event.read().last();
+
+    if let Some(event) = event {
+        active_search.filter = event.filter.clone().unwrap_or_default();
+        active_search.client_version = event.client_version;
     }
 
-    search_servers_event.clear();
+    if continuous.is_some() {
+        // the browse stays active across searches in continuous mode,
+        // staleness pruning (see `prune_stale_servers`) is what keeps the
+        // list current instead of a clear-and-rebrowse on every event
+        if event.is_none() || searching.is_some() {
+            return;
+        }
+    } else {
+        if event.is_none() {
+            return;
+        }
 
-    if !discovered_servers.is_empty() {
-        discovered_servers.0.clear();
+        if !discovered_servers.is_empty() {
+            // entries restored from the on-disk cache stay until a discovery
+            // backend confirms them, see `ServerBrowserPlugin::with_server_cache`
+            discovered_servers
+                .0
+                .retain(|_, server| server.status == DiscoveredServerStatus::Cached);
+        }
     }
 
     let service_type = format!("_{}._udp.local.", service.name);
@@ -301,7 +583,11 @@ fn register_server(mut commands: Commands, server: Res<DiscoverableServer>, serv
         &service_hostname,
         "",
         server.port,
-        server.metadata.clone(),
+        server
+            .metadata
+            .clone()
+            .with(VERSION_METADATA_KEY, server.version)
+            .into_hash_map(),
     )
     .expect("valid service info")
     .enable_addr_auto();
@@ -0,0 +1,340 @@
+//! Internet-wide server discovery through a central master server, modeled
+//! on the announce / challenge / query flow used by the xash3d master
+//! server. This runs alongside the mDNS backend in `lib.rs` and merges its
+//! results into the same [`DiscoveredServerList`], see
+//! [`crate::ServerBrowserPlugin::with_master`].
+
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{IpAddr, SocketAddr, UdpSocket},
+    time::{Duration, Instant},
+};
+
+use bevy_app::prelude::*;
+use bevy_ecs::prelude::*;
+use bevy_utils::tracing::warn;
+
+use crate::{
+    ActiveSearch, DiscoverableServer, DiscoveredServer, DiscoveredServerList,
+    DiscoveredServerStatus, SearchServers, ServerMetadata,
+};
+
+const TAG_ANNOUNCE: u8 = b'a';
+const TAG_CHALLENGE: u8 = b'c';
+const TAG_QUERY: u8 = b'q';
+const TAG_LIST: u8 = b'l';
+/// Sent instead of [`TAG_LIST`] when the master considers our
+/// [`SearchServers::client_version`] too old to safely connect to the
+/// servers it knows about.
+const TAG_UPDATE_REQUIRED: u8 = b'u';
+
+/// Key [`poll_master_socket`] stores the "you need to update" sentinel
+/// entry under, see [`TAG_UPDATE_REQUIRED`].
+const UPDATE_REQUIRED_KEY: &str = "master:update-required";
+
+const DEFAULT_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_CHALLENGE_TIMEOUT: Duration = Duration::from_secs(5);
+const DEFAULT_ENTRY_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Registers the master-server resources and systems on `app`. Called from
+/// [`crate::ServerBrowserPlugin::build`] when a master address was provided.
+pub(crate) fn build(app: &mut App, config: MasterConfig) {
+    app.insert_resource(config);
+    app.insert_resource(MasterAnnounceState::default());
+    app.insert_resource(MasterEntrySeen::default());
+    app.insert_resource(
+        MasterSocket::bind().expect("Could not open UDP socket for master server communication"),
+    );
+
+    app.add_systems(
+        PreUpdate,
+        (
+            announce_to_master.run_if(resource_exists::<DiscoverableServer>()),
+            query_master,
+        ),
+    );
+    app.add_systems(PostUpdate, poll_master_socket);
+}
+
+/// Configuration for the master-server discovery backend, see
+/// [`crate::ServerBrowserPlugin::with_master`] and
+/// [`crate::ServerBrowserPlugin::with_master_timeouts`].
+#[derive(Resource, Clone, Copy)]
+pub(crate) struct MasterConfig {
+    pub(crate) addr: SocketAddr,
+    /// How often an already-announced [`DiscoverableServer`] re-announces
+    /// itself so the master does not expire it.
+    pub(crate) announce_interval: Duration,
+    /// How long we wait for the master to answer an announce with a
+    /// challenge before re-sending the announce.
+    pub(crate) challenge_timeout: Duration,
+    /// How long a master-discovered server is kept in [`DiscoveredServerList`]
+    /// without being returned again by a query before it is pruned.
+    pub(crate) entry_timeout: Duration,
+}
+
+impl MasterConfig {
+    /// Builds a [`MasterConfig`] using the xash3d-derived default timeouts,
+    /// see [`crate::ServerBrowserPlugin::with_master`].
+    pub(crate) fn new(addr: SocketAddr) -> Self {
+        Self {
+            addr,
+            announce_interval: DEFAULT_ANNOUNCE_INTERVAL,
+            challenge_timeout: DEFAULT_CHALLENGE_TIMEOUT,
+            entry_timeout: DEFAULT_ENTRY_TIMEOUT,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MasterSocket(UdpSocket);
+
+impl MasterSocket {
+    fn bind() -> io::Result<Self> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        socket.set_nonblocking(true)?;
+        Ok(Self(socket))
+    }
+}
+
+/// Challenge/announce state for our own [`DiscoverableServer`], if any.
+#[derive(Resource, Default)]
+struct MasterAnnounceState {
+    /// Challenge number the master last sent us, echoed back on the next
+    /// announce to prove we own the source address we are announcing from.
+    challenge: Option<u32>,
+    last_sent: Option<Instant>,
+}
+
+/// Tracks when each master-discovered server was last confirmed by a query
+/// response, so entries that stop being returned can be pruned.
+#[derive(Resource, Default)]
+struct MasterEntrySeen(HashMap<String, Instant>);
+
+fn announce_to_master(
+    socket: Res<MasterSocket>,
+    config: Res<MasterConfig>,
+    server: Res<DiscoverableServer>,
+    mut state: ResMut<MasterAnnounceState>,
+) {
+    // until the master has confirmed us with a challenge, retry more
+    // eagerly than the steady-state announce interval
+    let interval = if state.challenge.is_some() {
+        config.announce_interval
+    } else {
+        config.challenge_timeout
+    };
+    let due = state
+        .last_sent
+        .map_or(true, |last_sent| last_sent.elapsed() >= interval);
+
+    if !server.is_changed() && !due {
+        return;
+    }
+
+    let packet = encode_announce(
+        state.challenge.unwrap_or(0),
+        server.port,
+        server.version,
+        &server.metadata,
+    );
+
+    if let Err(error) = socket.0.send_to(&packet, config.addr) {
+        warn!(
+            "Failed to send announce to master server {}: {error}",
+            config.addr
+        );
+        return;
+    }
+
+    state.last_sent = Some(Instant::now());
+}
+
+fn query_master(
+    socket: Res<MasterSocket>,
+    config: Res<MasterConfig>,
+    mut search_servers_event: EventReader<SearchServers>,
+) {
+    let Some(event) = search_servers_event.read().last() else {
+        return;
+    };
+
+    let mut packet = vec![TAG_QUERY];
+    packet.extend_from_slice(&event.client_version.to_le_bytes());
+
+    if let Err(error) = socket.0.send_to(&packet, config.addr) {
+        warn!("Failed to query master server {}: {error}", config.addr);
+    }
+}
+
+fn poll_master_socket(
+    socket: Res<MasterSocket>,
+    config: Res<MasterConfig>,
+    active_search: Res<ActiveSearch>,
+    mut announce_state: ResMut<MasterAnnounceState>,
+    mut discovered_servers: ResMut<DiscoveredServerList>,
+    mut seen: ResMut<MasterEntrySeen>,
+) {
+    let mut buf = [0u8; 1024];
+    // see the comment on the mDNS equivalent in `update_discovered_servers`
+    let servers = discovered_servers.bypass_change_detection();
+    let mut changed = false;
+
+    loop {
+        let (len, from) = match socket.0.recv_from(&mut buf) {
+            Ok(result) => result,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => break,
+            Err(error) => {
+                warn!("Failed to read from master socket: {error}");
+                break;
+            }
+        };
+
+        // the master drops entries whose source address does not match, we
+        // do the same for packets claiming to come from the master
+        if from != config.addr {
+            continue;
+        }
+
+        // match/slice over the datagram we actually received rather than
+        // `buf.first()` + `&buf[1..len]`: a zero-length datagram would read
+        // a stale tag byte left over from a previous, larger packet and
+        // then panic slicing `&buf[1..0]`. Source addresses are trivially
+        // spoofable, which is exactly the threat the challenge handshake
+        // above is meant to harden against, so a malformed/empty packet
+        // must not be able to crash us.
+        match buf[..len].split_first() {
+            Some((&TAG_CHALLENGE, payload)) => {
+                if let Some(challenge) = decode_challenge(payload) {
+                    announce_state.challenge = Some(challenge);
+                    // force an immediate re-announce echoing the challenge
+                    announce_state.last_sent = None;
+                }
+            }
+            Some((&TAG_LIST, payload)) => {
+                changed |= servers.forget(UPDATE_REQUIRED_KEY);
+
+                // the master only ever relays bare addresses, never
+                // metadata (see `DiscoveredServer::metadata`), so a
+                // metadata filter has nothing to match against here;
+                // applying it would drop every master entry whenever the
+                // caller sets any non-trivial filter predicate
+                for (address, port) in decode_server_list(payload) {
+                    let key = master_key(address, port);
+
+                    seen.0.insert(key.clone(), Instant::now());
+
+                    // the master already filters announces by protocol
+                    // version against the `client_version` we sent in our
+                    // query, so servers it returns are never individually
+                    // outdated
+                    let server = DiscoveredServer {
+                        hostname: address.to_string(),
+                        port,
+                        addresses: HashSet::from([address]),
+                        metadata: ServerMetadata::new(),
+                        version: active_search.client_version,
+                        outdated: false,
+                        status: DiscoveredServerStatus::Live,
+                    };
+
+                    changed |= servers.upsert(&key, server);
+                }
+            }
+            Some((&TAG_UPDATE_REQUIRED, _)) => {
+                // the master considers our client too old to safely connect
+                // to anything it knows about; surface a single distinguished
+                // entry instead of a server list, mirroring xash3d
+                let server = DiscoveredServer {
+                    hostname: "master-update-required".to_string(),
+                    port: 0,
+                    addresses: HashSet::new(),
+                    metadata: ServerMetadata::new(),
+                    version: 0,
+                    outdated: true,
+                    status: DiscoveredServerStatus::Live,
+                };
+
+                changed |= servers.upsert(UPDATE_REQUIRED_KEY, server);
+            }
+            _ => {}
+        }
+    }
+
+    seen.0.retain(|key, last_seen| {
+        if last_seen.elapsed() < config.entry_timeout {
+            true
+        } else {
+            changed |= servers.forget(key);
+            false
+        }
+    });
+
+    if changed {
+        discovered_servers.set_changed();
+    }
+}
+
+fn master_key(address: IpAddr, port: u16) -> String {
+    format!("master:{address}:{port}")
+}
+
+fn encode_announce(challenge: u32, port: u16, version: u32, metadata: &ServerMetadata) -> Vec<u8> {
+    let mut packet = vec![TAG_ANNOUNCE];
+    packet.extend_from_slice(&challenge.to_le_bytes());
+    packet.extend_from_slice(&port.to_le_bytes());
+    packet.extend_from_slice(&version.to_le_bytes());
+    packet.extend_from_slice(&metadata.encode());
+    packet
+}
+
+fn decode_challenge(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_le_bytes(payload.get(0..4)?.try_into().ok()?))
+}
+
+fn decode_server_list(payload: &[u8]) -> Vec<(IpAddr, u16)> {
+    let mut entries = Vec::new();
+    let mut cursor = 0;
+
+    let count = match payload.get(0..2) {
+        Some(bytes) => u16::from_le_bytes(bytes.try_into().expect("slice has len 2")),
+        None => return entries,
+    };
+    cursor += 2;
+
+    for _ in 0..count {
+        let Some(&tag) = payload.get(cursor) else {
+            break;
+        };
+        cursor += 1;
+
+        let address = match tag {
+            4 => {
+                let Some(bytes) = payload.get(cursor..cursor + 4) else {
+                    break;
+                };
+                cursor += 4;
+                IpAddr::from(<[u8; 4]>::try_from(bytes).expect("slice has len 4"))
+            }
+            6 => {
+                let Some(bytes) = payload.get(cursor..cursor + 16) else {
+                    break;
+                };
+                cursor += 16;
+                IpAddr::from(<[u8; 16]>::try_from(bytes).expect("slice has len 16"))
+            }
+            _ => break,
+        };
+
+        let Some(port_bytes) = payload.get(cursor..cursor + 2) else {
+            break;
+        };
+        cursor += 2;
+        let port = u16::from_le_bytes(port_bytes.try_into().expect("slice has len 2"));
+
+        entries.push((address, port));
+    }
+
+    entries
+}
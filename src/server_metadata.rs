@@ -40,6 +40,11 @@ impl ServerMetadata {
         self.0.entry_ref(key.as_ref()).insert(value.to_string());
     }
 
+    /// Removes a key, returning its previous value if it was present.
+    pub(crate) fn remove<K: AsRef<str>>(&mut self, key: K) -> Option<String> {
+        self.0.remove(key.as_ref())
+    }
+
     /// Sets the value of a key and returns self.
     /// This function is useful for chaining metadata creation:
     /// ```
@@ -57,6 +62,22 @@ impl ServerMetadata {
     pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
         self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
     }
+
+    /// Encodes metadata as `count: u16` followed by `count` repetitions of
+    /// `key_len: u8, key, value_len: u8, value`, used for sending metadata
+    /// over the wire to the master server, see the `master` module.
+    pub(crate) fn encode(&self) -> Vec<u8> {
+        let mut bytes = (self.0.len() as u16).to_le_bytes().to_vec();
+
+        for (key, value) in self.0.iter() {
+            bytes.push(key.len() as u8);
+            bytes.extend_from_slice(key.as_bytes());
+            bytes.push(value.len() as u8);
+            bytes.extend_from_slice(value.as_bytes());
+        }
+
+        bytes
+    }
 }
 
 impl Debug for ServerMetadata {